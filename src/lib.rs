@@ -11,6 +11,12 @@
 //! (from where this functionality was ruthlessly copied),
 //! i.e., using the environment variable RUST_LOG.
 //!
+//! A spec can still carry an optional `/regex` part to filter on the message text, but its
+//! meaning is **not** the same as plain env_logger: a bare pattern is now include-only (only
+//! matching messages are logged), and a `!`-prefixed pattern excludes matches (the behavior a
+//! bare pattern used to have). Existing `RUST_LOG=mymod/pattern` values need a leading `!` added
+//! to keep their old, exclude-on-match meaning.
+//!
 //!  Only the initialization is a bit more chatty due to the configurability.
 //!
 //!
@@ -32,11 +38,11 @@
 //! if you have e.g. a command-line option ```--loglevelspec```:
 //!
 //! ```
-//! use flexi_logger::{detailed_format, LogConfig};
+//! use flexi_logger::{detailed_format, LogConfig, LogTarget};
 //!
 //!     flexi_logger::init( LogConfig {
-//!                             log_to_file: true,
-//!                             format: flexi_logger::detailed_format,
+//!                             targets: vec![LogTarget::File],
+//!                             format: Box::new(flexi_logger::detailed_format),
 //!                             .. LogConfig::new()  // use defaults for all other options
 //!                         },
 //!                         args.flag_loglevelspec
@@ -44,44 +50,400 @@
 //! ```
 //!
 //! Flexi_logger comes with two predefined format variants, ```default_format()``` and ```detailed_format()```,
-//! but you can easily create and use your own format function with the signature ```fn(&LogRecord) -> String```.
+//! but you can easily create and use your own format function with the signature
+//! ```fn(&mut Write, &LogRecord) -> io::Result<()>```. Formatters using the older
+//! ```fn(&LogRecord) -> String``` signature keep working via `adapt_owned_format`.
+//! Colored counterparts of both, ```colored_default_format()``` and ```colored_detailed_format()```,
+//! are provided as well.
 //!
 
 
+extern crate atty;
 extern crate log;
 extern crate regex;
 extern crate time;
+#[cfg(feature = "syslog")]
+extern crate syslog;
 
 use log::{Log, LogLevel, LogLevelFilter, LogMetadata};
 pub use log::LogRecord;
 use regex::Regex;
 use std::env;
 use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::io;
+use std::cmp;
 use std::io::{LineWriter, Write};
+use std::mem;
 use std::ops::Add;
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::str;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Controls how the name of the trace file is derived, and of the rotated files once either
+/// `rotate_over_size` or `rotate_daily` is configured.
+#[derive(Clone, Copy)]
+pub enum RotateNaming {
+    /// Every file, including the first one, gets its own timestamp in its name, e.g.
+    /// `myprog_2015-07-08_10-44-11.trc`. If two rotations land in the same second, a numeric
+    /// tie-breaker (`_2`, `_3`, ...) is appended so neither file is overwritten.
+    Timestamps,
+    /// The currently written file is always `<program>.trc`. On rotation it is renamed to
+    /// `<program>.1.trc`, the previous `.1` becomes `.2`, and so on, bounded by `keep_count`
+    /// when set; a fresh `<program>.trc` is then opened.
+    Numbers,
+}
+
+/// Controls whether the level token in a formatted logline is wrapped in ANSI color.
+/// Never affects `LogTarget::File`, which always receives plain text.
+#[derive(Clone, Copy)]
+pub enum ColorMode {
+    /// Colorize only if stderr is connected to a terminal.
+    Auto,
+    /// Always colorize.
+    Always,
+    /// Never colorize.
+    Never,
+}
+
+fn level_color_code(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Error => "\x1b[31m",
+        LogLevel::Warn  => "\x1b[33m",
+        LogLevel::Info  => "\x1b[32m",
+        LogLevel::Debug | LogLevel::Trace => "\x1b[2m",
+    }
+}
+const COLOR_RESET: &'static str = "\x1b[0m";
+
+/// Wraps the level token (e.g. `ERROR`) found in `msg` in the color appropriate for `level`.
+fn colorize(level: LogLevel, msg: &str) -> String {
+    let token = level.to_string();
+    match msg.find(&token) {
+        Some(pos) => {
+            let mut out = String::with_capacity(msg.len() + level_color_code(level).len() + COLOR_RESET.len());
+            out.push_str(&msg[..pos]);
+            out.push_str(level_color_code(level));
+            out.push_str(&token);
+            out.push_str(COLOR_RESET);
+            out.push_str(&msg[pos + token.len()..]);
+            out
+        },
+        None => msg.to_string(),
+    }
+}
+
+/// Drops any ANSI `ESC [ ... m` color sequences from `s`. A bare `ESC` not followed by `[` is
+/// not a sequence we emit, so it is passed through untouched rather than eating the rest of
+/// the line up to the next `m`.
+fn strip_ansi_codes(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next(); // consume '['
+            for c2 in chars.by_ref() {
+                if c2 == 'm' { break; }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Tracks the state of the currently open trace file that rotation decisions are based on, plus
+/// the retention bookkeeping `keep_count` needs. Which field is used depends on `rotate_naming`:
+/// `Numbers` renames the file currently on disk at rotation time, so it only needs to remember
+/// how many numbered files already exist (`rotated_count`); `Timestamps` never renames a file
+/// once written, so it remembers the concrete paths of earlier rotations instead (`history`,
+/// oldest first), since those can't be derived from a count.
+struct FileState {
+    writer: LineWriter<File>,
+    written_bytes: u64,
+    day: String,
+    path: String,
+    rotated_count: usize,
+    history: Vec<String>,
+}
+impl FileState {
+    fn new(path: String, rotated_count: usize, history: Vec<String>) -> FileState {
+        // we die hard if the log file cannot be opened
+        let writer = LineWriter::new(File::create(&path).unwrap());
+        FileState {
+            writer: writer,
+            written_bytes: 0,
+            day: current_day(),
+            path: path,
+            rotated_count: rotated_count,
+            history: history,
+        }
+    }
+}
+
+fn current_day() -> String {
+    time::strftime("%Y-%m-%d", &time::now()).unwrap()
+}
+
+/// A sink that a fully formatted log line can be written to; `init` turns every `LogTarget`
+/// into one of these.
+///
+/// No `LogTarget` takes a `LogWriter` implementation directly: a user-supplied destination is
+/// handed in as an `io::Write` via `LogTarget::Writer`.
+pub trait LogWriter: Send + Sync {
+    /// Writes `formatted_msg`, which already ends in a newline, for a record logged at `level`.
+    fn write(&self, level: LogLevel, formatted_msg: &str);
+
+    /// Whether this sink should receive the ANSI-colorized message when `LogConfig::colored`
+    /// resolves to true. Defaults to `false`, so color codes don't leak into files, custom
+    /// sinks, or syslog; only `StdErrLogWriter` overrides this.
+    fn accepts_color(&self) -> bool {
+        false
+    }
+}
+
+/// Writes into the rotating trace file described by `LogConfig`'s `rotate_*` and `keep_count`
+/// fields.
+struct FileLogWriter {
+    basename: String,
+    state: Mutex<FileState>,
+    rotate_over_size: Option<u64>,
+    rotate_daily: bool,
+    rotate_naming: RotateNaming,
+    keep_count: Option<usize>,
+}
+impl FileLogWriter {
+    fn new(basename: String, initial_path: &str, rotate_over_size: Option<u64>, rotate_daily: bool,
+           rotate_naming: RotateNaming, keep_count: Option<usize>) -> FileLogWriter {
+        FileLogWriter {
+            basename: basename,
+            state: Mutex::new(FileState::new(initial_path.to_string(), 0, Vec::new())),
+            rotate_over_size: rotate_over_size,
+            rotate_daily: rotate_daily,
+            rotate_naming: rotate_naming,
+            keep_count: keep_count,
+        }
+    }
+
+    /// Decides whether the currently open file needs to be rolled before `incoming_len`
+    /// further bytes are written to it, and performs the roll if so.
+    fn rotate_if_needed(&self, state: &mut FileState, incoming_len: u64) {
+        let size_exceeded = self.rotate_over_size
+            .map_or(false, |max| state.written_bytes + incoming_len > max);
+        let day_changed = self.rotate_daily && state.day != current_day();
+        if !size_exceeded && !day_changed {
+            return;
+        }
+        state.writer.flush().unwrap_or_else(|e|{panic!("File logger: flush failed with {}",e)});
 
+        let (next_path, rotated_count, history) = match self.rotate_naming {
+            RotateNaming::Numbers => {
+                self.roll_numbered_files(state.rotated_count);
+                let rotated_count = match self.keep_count {
+                    Some(keep_count) => cmp::min(state.rotated_count + 1, keep_count),
+                    None => state.rotated_count + 1,
+                };
+                (format!("{}.trc", self.basename), rotated_count, Vec::new())
+            }
+            RotateNaming::Timestamps => {
+                let mut history = Vec::new();
+                mem::swap(&mut history, &mut state.history);
+                history.push(state.path.clone());
+                if let Some(keep_count) = self.keep_count {
+                    while history.len() > keep_count {
+                        let oldest = history.remove(0);
+                        let _ = fs::remove_file(oldest);
+                    }
+                }
+                (self.next_timestamped_path(), 0, history)
+            }
+        };
+        *state = FileState::new(next_path, rotated_count, history);
+    }
+
+    /// Shifts `<basename>.N.trc` to `<basename>.(N+1).trc` for every one of the `current_count`
+    /// files already on disk, dropping the oldest once `keep_count` is exceeded, and finally
+    /// moves the active `<basename>.trc` to `<basename>.1.trc` (or deletes it if `keep_count` is
+    /// 0), freeing that name up for the fresh file `rotate_if_needed` opens next.
+    fn roll_numbered_files(&self, current_count: usize) {
+        if let Some(keep_count) = self.keep_count {
+            if current_count + 1 > keep_count {
+                let _ = fs::remove_file(format!("{}.{}.trc", self.basename, keep_count));
+            }
+        }
+        let mut i = current_count;
+        while i >= 1 {
+            let _ = fs::rename(format!("{}.{}.trc", self.basename, i), format!("{}.{}.trc", self.basename, i + 1));
+            i -= 1;
+        }
+        let active = format!("{}.trc", self.basename);
+        if self.keep_count == Some(0) {
+            let _ = fs::remove_file(active);
+        } else {
+            let _ = fs::rename(active, format!("{}.1.trc", self.basename));
+        }
+    }
+
+    /// Computes the path of the next timestamped file. The 1-second-resolution stamp can repeat
+    /// under bursty rotation; a numeric tie-breaker is appended in that case so an earlier file
+    /// in the same second is never truncated.
+    fn next_timestamped_path(&self) -> String {
+        let s_timestamp = time::strftime("_%Y-%m-%d_%H-%M-%S",&time::now()).unwrap();
+        let first = format!("{}{}.trc", self.basename, s_timestamp);
+        if !Path::new(&first).exists() {
+            return first;
+        }
+        let mut tie = 2;
+        loop {
+            let candidate = format!("{}{}_{}.trc", self.basename, s_timestamp, tie);
+            if !Path::new(&candidate).exists() {
+                return candidate;
+            }
+            tie += 1;
+        }
+    }
+}
+impl LogWriter for FileLogWriter {
+    fn write(&self, _level: LogLevel, formatted_msg: &str) {
+        // colorization is opt-out here: a `.trc` file must never contain ANSI escape codes,
+        // even if `format`/`colored` colorize the same message for other targets. Stripping
+        // allocates, so only pay for it when the message actually contains an escape code.
+        let owned;
+        let msgb: &[u8] = if formatted_msg.contains('\x1b') {
+            owned = strip_ansi_codes(formatted_msg);
+            owned.as_bytes()
+        } else {
+            formatted_msg.as_bytes()
+        };
+        let mut state = self.state.lock().unwrap(); // FIXME correct error handling
+        self.rotate_if_needed(&mut state, msgb.len() as u64);
+        state.writer.write(msgb).unwrap_or_else( |e|{panic!("File logger: write failed with {}",e)} );
+        state.written_bytes += msgb.len() as u64;
+    }
+}
+
+/// Writes to stderr.
+struct StdErrLogWriter;
+impl LogWriter for StdErrLogWriter {
+    fn write(&self, _level: LogLevel, formatted_msg: &str) {
+        let _ = write!(&mut io::stderr(), "{}", formatted_msg);
+    }
+
+    fn accepts_color(&self) -> bool {
+        true
+    }
+}
+
+/// Writes to stdout.
+struct StdOutLogWriter;
+impl LogWriter for StdOutLogWriter {
+    fn write(&self, _level: LogLevel, formatted_msg: &str) {
+        let _ = write!(&mut io::stdout(), "{}", formatted_msg);
+    }
+}
+
+/// Writes into a user-supplied sink, as selected via `LogTarget::Writer`.
+struct CustomLogWriter {
+    writer: Mutex<Box<Write + Send>>,
+}
+impl LogWriter for CustomLogWriter {
+    fn write(&self, _level: LogLevel, formatted_msg: &str) {
+        let mut writer = self.writer.lock().unwrap(); // FIXME correct error handling
+        let _ = write!(writer, "{}", formatted_msg);
+    }
+}
+
+/// Wraps another `LogWriter` and only forwards records logged at exactly `level`, stripping any
+/// color first; used to implement `LogConfig::duplicate_error`/`duplicate_info`, which must stay
+/// plain text regardless of whether the primary target is colorized.
+struct ExactLevelLogWriter {
+    level: LogLevel,
+    inner: Box<LogWriter>,
+}
+impl LogWriter for ExactLevelLogWriter {
+    fn write(&self, level: LogLevel, formatted_msg: &str) {
+        if level != self.level {
+            return;
+        }
+        let plain_msg = strip_ansi_codes(formatted_msg);
+        self.inner.write(level, &plain_msg);
+    }
+}
+
+/// The syslog facility an emitted record is tagged with; re-exported so callers don't need
+/// to depend on the `syslog` crate themselves.
+#[cfg(feature = "syslog")]
+pub use syslog::Facility;
+
+/// Ships formatted log lines to the local syslog daemon; selected via `LogTarget::Syslog`.
+#[cfg(feature = "syslog")]
+struct SyslogLogWriter {
+    logger: Mutex<syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>>,
+}
+#[cfg(feature = "syslog")]
+impl SyslogLogWriter {
+    fn new(facility: Facility, program_name: String) -> SyslogLogWriter {
+        let formatter = syslog::Formatter3164 {
+            facility: facility,
+            hostname: None,
+            process: program_name,
+            pid: 0,
+        };
+        let logger = syslog::unix(formatter)
+            .unwrap_or_else(|e|{panic!("Syslog writer: connection failed with {}", e)});
+        SyslogLogWriter { logger: Mutex::new(logger) }
+    }
+}
+#[cfg(feature = "syslog")]
+impl LogWriter for SyslogLogWriter {
+    fn write(&self, level: LogLevel, formatted_msg: &str) {
+        let mut logger = self.logger.lock().unwrap(); // FIXME correct error handling
+        let _ = match level {
+            LogLevel::Error => logger.err(formatted_msg),
+            LogLevel::Warn => logger.warning(formatted_msg),
+            LogLevel::Info => logger.info(formatted_msg),
+            LogLevel::Debug | LogLevel::Trace => logger.debug(formatted_msg),
+        };
+    }
+}
+
+/// The type of a logline formatter: it writes a logline for `record` directly into `w`,
+/// rather than allocating and returning a `String`.
+pub type Format = Fn(&mut Write, &LogRecord) -> io::Result<()> + Send + Sync;
+
+/// Adapts a formatter using the old `fn(&LogRecord) -> String` signature to the buffer-writing
+/// `Format` signature, for custom formatters that have not been rewritten yet.
+pub fn adapt_owned_format(old: fn(&LogRecord) -> String) -> Box<Format> {
+    Box::new(move |w: &mut Write, record: &LogRecord| write!(w, "{}", old(record)))
+}
+
+/// Whether a parsed `/regex` filter keeps matching messages or drops them.
+#[derive(Clone, Copy)]
+enum FilterMode {
+    /// A plain pattern: drop messages that do *not* match (keep only matches).
+    Include,
+    /// A `!`-prefixed pattern: drop messages that match (the original behavior).
+    Exclude,
+}
 
 struct FlexiLogger{
-    directives: Vec<LogDirective>,
-    filter: Option<Regex>,
-    line_writer: Arc<Mutex<LineWriter<File>>>,
-    config: LogConfig
+    directives: Arc<RwLock<Vec<LogDirective>>>,
+    filter: Option<(FilterMode, Regex)>,
+    writers: Vec<Box<LogWriter>>,
+    format: Box<Format>,
+    colored: bool,
 }
 impl FlexiLogger {
-    fn new( directives: Vec<LogDirective>, filter: Option<Regex>,
-            logfile_path:&str, config: LogConfig) -> FlexiLogger  {
-        // we die hard if the log file cannot be opened
-        let line_writer = Arc::new(Mutex::new( LineWriter::new(File::create(logfile_path.clone()).unwrap()) ));
-        FlexiLogger {directives: directives,filter: filter, line_writer: line_writer, config: config }
+    fn new( directives: Arc<RwLock<Vec<LogDirective>>>, filter: Option<(FilterMode, Regex)>, writers: Vec<Box<LogWriter>>,
+            format: Box<Format>, colored: bool) -> FlexiLogger  {
+        FlexiLogger {directives: directives, filter: filter, writers: writers, format: format, colored: colored }
     }
 
     fn ml_enabled(&self, level: LogLevel, target: &str) -> bool {
+        let directives = self.directives.read().unwrap(); // FIXME correct error handling
         // Search for the longest match, the vector is assumed to be pre-sorted.
-        for directive in self.directives.iter().rev() {
+        for directive in directives.iter().rev() {
             match directive.name {
                 Some(ref name) if !target.starts_with(&**name) => {},
                 Some(..) | None => {
@@ -102,25 +464,39 @@ impl Log for FlexiLogger {
             return;
         }
 
-        if let Some(filter) = self.filter.as_ref() {
-            if filter.is_match(&*record.args().to_string()) {
+        if let Some(&(mode, ref filter)) = self.filter.as_ref() {
+            let is_match = filter.is_match(&*record.args().to_string());
+            let drop = match mode {
+                FilterMode::Exclude => is_match,
+                FilterMode::Include => !is_match,
+            };
+            if drop {
                 return;
             }
         }
 
-        let mut msg = (self.config.format)(record);
-        msg.push('\n');
-        if self.config.log_to_file {
-            if self.config.duplicate_error && record.level() == LogLevel::Error
-            || self.config.duplicate_info  && record.level() == LogLevel::Info {
-                println!("{}",&record.args());
+        let mut buf: Vec<u8> = Vec::new();
+        (self.format)(&mut buf, record).unwrap_or_else(|e|{panic!("Formatting failed with {}",e)});
+        buf.push(b'\n');
+        let plain = str::from_utf8(&buf).unwrap_or_else(|e|{panic!("Formatter produced invalid utf8: {}",e)});
+
+        // Only colorization needs an owned copy; the common, uncolored case writes the
+        // formatted bytes straight to the sinks without any further allocation. Even then,
+        // only writers that opt in via `accepts_color` (stderr) get the colorized copy, so
+        // files, custom sinks, and syslog never see the escape codes that `colored` adds.
+        if self.colored {
+            let colored_msg = colorize(record.level(), plain);
+            for writer in &self.writers {
+                if writer.accepts_color() {
+                    writer.write(record.level(), &colored_msg);
+                } else {
+                    writer.write(record.level(), plain);
+                }
             }
-            let msgb = msg.as_bytes();
-            let lw = self.line_writer.clone();
-            let mut lw1 = lw.lock().unwrap(); // FIXME correct error handling
-            lw1.write(msgb).unwrap_or_else( |e|{panic!("File logger: write failed with {}",e)} );
         } else {
-            let _ = writeln!(&mut io::stderr(), "{}", msg );
+            for writer in &self.writers {
+                writer.write(record.level(), plain);
+            }
         }
     }
 }
@@ -141,44 +517,85 @@ impl fmt::Display for  FlexiLoggerError {
     }
 }
 
+/// A destination that a log record can be written to; one `LogConfig` can select several,
+/// and every record is fanned out to all of them.
+pub enum LogTarget {
+    /// The rotating trace file; see `LogConfig`'s `rotate_*` and `keep_count` fields.
+    File,
+    /// Standard error.
+    StdErr,
+    /// Standard output.
+    StdOut,
+    /// A user-supplied sink, e.g. a socket or an in-memory buffer.
+    Writer(Box<Write + Send>),
+    /// Ship records to the local syslog daemon under the given facility, tagged with the
+    /// program name. Requires the `syslog` cargo feature.
+    #[cfg(feature = "syslog")]
+    Syslog {
+        /// The syslog facility to log under, e.g. `Facility::LOG_USER`.
+        facility: Facility,
+    },
+}
+
 /// Allows influencing the behavior of the FlexiLogger.
 pub struct LogConfig {
-    /// If `true`, the log is written to a file. Default is `false`, the log is then
-    /// written to stderr.
-    /// If `true`, a new file in the current directory is created and written to.
-    /// The name of the file is chosen as '\<program_name\>\_\<date\>\_\<time\>.trc', e.g. `myprog_2015-07-08_10-44-11.trc`
-    pub log_to_file: bool,
-    /// If `true` (which is default), and if `log_to_file` is `true`, the name of the tracefile is documented in a message to stdout.
+    /// The destinations every log record is written to. Default is `vec![LogTarget::StdErr]`.
+    pub targets: Vec<LogTarget>,
+    /// If `true` (which is default), and if `LogTarget::File` is among `targets`, the name of the tracefile is documented in a message to stdout.
     pub print_message: bool,
-    /// If `true` (which is default), and if `log_to_file` is `true`, all error messages are written also to stdout.
+    /// If `true` (which is default), and if `LogTarget::File` is among `targets`, all error messages are additionally written to stderr.
     pub duplicate_error: bool,
-    /// If `true` (which is default), and if `log_to_file` is `true`, also info messages are written also to stdout.
+    /// If `true` (default is `false`), and if `LogTarget::File` is among `targets`, also info messages are additionally written to stderr.
     pub duplicate_info: bool,
-    /// Allows providing a custom logline format; default is flexi_logger::default_format.
-    pub format: fn(&LogRecord) -> String,
+    /// Allows providing a custom logline formatter; default is flexi_logger::default_format.
+    /// Old-style `fn(&LogRecord) -> String` formatters can be plugged in via `adapt_owned_format`.
+    pub format: Box<Format>,
+    /// If set, and if `LogTarget::File` is among `targets`, a new file is started as soon as
+    /// appending the next logline would make the current file exceed the given size, in bytes.
+    /// Default is `None`, i.e., no size-based rotation.
+    pub rotate_over_size: Option<u64>,
+    /// If `true`, and if `LogTarget::File` is among `targets`, a new file is started as soon as
+    /// the calendar day changes. Default is `false`.
+    pub rotate_daily: bool,
+    /// Defines how the trace file is named, and how rotated files are named if
+    /// `rotate_over_size` or `rotate_daily` is used. Default is `RotateNaming::Timestamps`.
+    pub rotate_naming: RotateNaming,
+    /// If set, and if rotation is active, only the given number of rolled files is kept,
+    /// older ones are deleted. Default is `None`, i.e., rolled files are kept forever.
+    pub keep_count: Option<usize>,
+    /// Controls whether the level token of a formatted logline is colorized. Never affects
+    /// `LogTarget::File`, which always receives plain text. Default is `ColorMode::Auto`.
+    pub colored: ColorMode,
 }
 impl LogConfig {
     pub fn new() -> LogConfig {
         LogConfig {
-            log_to_file: false,
+            targets: vec![LogTarget::StdErr],
             print_message: true,
             duplicate_error: true,
             duplicate_info: false,
-            format: default_format,
+            format: Box::new(default_format),
+            rotate_over_size: None,
+            rotate_daily: false,
+            rotate_naming: RotateNaming::Timestamps,
+            keep_count: None,
+            colored: ColorMode::Auto,
         }
     }
 }
 
-/// A logline-formatter that produces lines like <br>
-/// ```INFO [my_prog::some_submodel] Task successfully read from conf.json```
-pub fn default_format(record: &LogRecord) -> String {
-    format!( "{} [{}] {}", record.level(), record.location().module_path(), record.args() )
+/// A logline-formatter that writes lines like <br>
+/// ```INFO [my_prog::some_submodel] Task successfully read from conf.json``` <br>
+/// directly into `w`, avoiding the per-call `String` allocation that the old
+/// `fn(&LogRecord) -> String` signature required.
+pub fn default_format(w: &mut Write, record: &LogRecord) -> io::Result<()> {
+    write!(w, "{} [{}] {}", record.level(), record.location().module_path(), record.args())
 }
 
-/// A logline-formatter that produces lines like <br>
+/// A logline-formatter that writes lines like <br>
 /// ```[2015-07-08 12:12:32:639785] INFO [my_prog::some_submodel] src/some_submodel.rs:26: Task successfully read from conf.json```
 #[allow(unused)]
-pub fn detailed_format(record: &LogRecord) -> String {
+pub fn detailed_format(w: &mut Write, record: &LogRecord) -> io::Result<()> {
     let timespec = time::get_time(); // high-precision now
     let tm = time::at(timespec);     // formattable. but low-precision now
     let mut time: String = time::strftime("%Y-%m-%d %H:%M:%S:", &tm).unwrap();
@@ -187,7 +604,7 @@ pub fn detailed_format(record: &LogRecord) -> String {
     let mut s = tmp.to_string();
     s.remove(9);s.remove(8);s.remove(7);s.remove(0);
     time = time.add(&s);
-    format!( "[{}] {} [{}] {}:{}: {}",
+    write!(w, "[{}] {} [{}] {}:{}: {}",
                 &time,
                 record.level(),
                 record.location().module_path(),
@@ -196,18 +613,71 @@ pub fn detailed_format(record: &LogRecord) -> String {
                 &record.args())
 }
 
+/// Like `default_format`, but wraps the level token in a color appropriate for it
+/// (red for errors, yellow for warnings, green for info, dim for debug/trace).
+pub fn colored_default_format(w: &mut Write, record: &LogRecord) -> io::Result<()> {
+    let mut buf = Vec::new();
+    try!(default_format(&mut buf, record));
+    let plain = String::from_utf8(buf).unwrap_or_else(|e|{panic!("Formatter produced invalid utf8: {}",e)});
+    write!(w, "{}", colorize(record.level(), &plain))
+}
+
+/// Like `detailed_format`, but wraps the level token in a color appropriate for it.
+#[allow(unused)]
+pub fn colored_detailed_format(w: &mut Write, record: &LogRecord) -> io::Result<()> {
+    let mut buf = Vec::new();
+    try!(detailed_format(&mut buf, record));
+    let plain = String::from_utf8(buf).unwrap_or_else(|e|{panic!("Formatter produced invalid utf8: {}",e)});
+    write!(w, "{}", colorize(record.level(), &plain))
+}
+
 struct LogDirective {
     name: Option<String>,
     level: LogLevelFilter,
 }
 
+/// Sorts `directives` by the length of their name, which allows a little more efficient
+/// longest-match lookup at runtime, and returns the overall maximum level across all of them.
+fn normalize_directives(directives: &mut Vec<LogDirective>) -> LogLevelFilter {
+    directives.sort_by(|a, b| {
+        let alen = a.name.as_ref().map(|a| a.len()).unwrap_or(0);
+        let blen = b.name.as_ref().map(|b| b.len()).unwrap_or(0);
+        alen.cmp(&blen)
+    });
+    directives.iter().map(|d| d.level).max().unwrap_or(LogLevelFilter::Off)
+}
+
+/// A handle returned by `init` that allows raising or lowering the active log verbosity at
+/// runtime, e.g. in response to a SIGHUP or an admin command, without restarting the process.
+pub struct ReloadHandle {
+    directives: Arc<RwLock<Vec<LogDirective>>>,
+    max_level: log::MaxLogLevelFilter,
+}
+impl ReloadHandle {
+    /// Re-parses `spec` (same grammar as the `RUST_LOG` environment variable, see
+    /// `parse_logging_spec`) and atomically swaps it in as the new set of directives.
+    /// A `/regex` part in `spec` is parsed but ignored; the message filter installed at
+    /// `init` time is not reloadable.
+    pub fn set_new_spec(&self, spec: &str) {
+        let (mut directives, _filter) = parse_logging_spec(spec);
+        let level = normalize_directives(&mut directives);
+        *self.directives.write().unwrap() = directives; // FIXME correct error handling
+        self.max_level.set(level);
+    }
+}
+
 /// Initializes the global logger with a flexi logger.
 ///
 /// This should be called early in the execution of a Rust program. Note that the
 /// global logger may only be initialized once, subsequent initialization attempts
 /// will return an error.
-pub fn init(config: LogConfig, loglevelspec: Option<String>) -> Result<(),FlexiLoggerError> {
-    log::set_logger( |max_level| {
+pub fn init(config: LogConfig, loglevelspec: Option<String>) -> Result<ReloadHandle,FlexiLoggerError> {
+    let directives_holder: Arc<RwLock<Vec<LogDirective>>> = Arc::new(RwLock::new(Vec::new()));
+    let max_level_holder: Arc<Mutex<Option<log::MaxLogLevelFilter>>> = Arc::new(Mutex::new(None));
+    let directives_for_closure = directives_holder.clone();
+    let max_level_for_closure = max_level_holder.clone();
+
+    try!(log::set_logger( move |max_level| {
         let (mut directives, filter) =
             match loglevelspec {
                 Some(ref llspec) => {let spec: &str = llspec; parse_logging_spec(&spec)},
@@ -219,33 +689,62 @@ pub fn init(config: LogConfig, loglevelspec: Option<String>) -> Result<(),FlexiL
                 }
             };
 
-        // Sort the provided directives by length of their name, this allows a
-        // little more efficient lookup at runtime.
-        directives.sort_by(|a, b| {
-            let alen = a.name.as_ref().map(|a| a.len()).unwrap_or(0);
-            let blen = b.name.as_ref().map(|b| b.len()).unwrap_or(0);
-            alen.cmp(&blen)
-        });
-
-        let level = {
-            let max = directives.iter().map(|d| d.level).max();
-            max.unwrap_or(LogLevelFilter::Off)
-        };
+        let level = normalize_directives(&mut directives);
         max_level.set(level);
+        *max_level_for_closure.lock().unwrap() = Some(max_level);
+        *directives_for_closure.write().unwrap() = directives;
         let arg0 = env::args().next().unwrap();
-        let filename = Path::new(&arg0).file_stem().unwrap().to_string_lossy();
-        let s_timestamp = time::strftime("_%Y-%m-%d_%H-%M-%S",&time::now()).unwrap();
-        let s_path = String::with_capacity(50).add(&filename).add(&s_timestamp).add(".trc");
-        if config.print_message {
-            println!("Trace is written to {}", &s_path);
+        let basename = Path::new(&arg0).file_stem().unwrap().to_string_lossy().into_owned();
+        let wants_file = config.targets.iter().any(|t| if let LogTarget::File = *t { true } else { false });
+
+        let mut writers: Vec<Box<LogWriter>> = Vec::new();
+        for target in config.targets {
+            writers.push(match target {
+                LogTarget::File => {
+                    let s_path = match config.rotate_naming {
+                        RotateNaming::Timestamps => {
+                            let s_timestamp = time::strftime("_%Y-%m-%d_%H-%M-%S",&time::now()).unwrap();
+                            String::with_capacity(50).add(&basename).add(&s_timestamp).add(".trc")
+                        },
+                        RotateNaming::Numbers => String::with_capacity(20).add(&basename).add(".trc"),
+                    };
+                    if config.print_message {
+                        println!("Trace is written to {}", &s_path);
+                    }
+                    Box::new(FileLogWriter::new(basename.clone(), &s_path, config.rotate_over_size,
+                                                 config.rotate_daily, config.rotate_naming, config.keep_count)) as Box<LogWriter>
+                },
+                LogTarget::StdErr => Box::new(StdErrLogWriter) as Box<LogWriter>,
+                LogTarget::StdOut => Box::new(StdOutLogWriter) as Box<LogWriter>,
+                LogTarget::Writer(w) => Box::new(CustomLogWriter { writer: Mutex::new(w) }) as Box<LogWriter>,
+                #[cfg(feature = "syslog")]
+                LogTarget::Syslog { facility } => Box::new(SyslogLogWriter::new(facility, basename.clone())) as Box<LogWriter>,
+            });
+        }
+        if wants_file && config.duplicate_error {
+            writers.push(Box::new(ExactLevelLogWriter { level: LogLevel::Error, inner: Box::new(StdErrLogWriter) }));
+        }
+        if wants_file && config.duplicate_info {
+            writers.push(Box::new(ExactLevelLogWriter { level: LogLevel::Info, inner: Box::new(StdErrLogWriter) }));
         }
-        Box::new(FlexiLogger::new(directives,filter,&s_path,config))
-    }).map_err(|_|{FlexiLoggerError::new("Logger initialization failed")})
+        let colored = match config.colored {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => atty::is(atty::Stream::Stderr),
+        };
+
+        Box::new(FlexiLogger::new(directives_for_closure.clone(),filter,writers,config.format,colored))
+    }).map_err(|_|{FlexiLoggerError::new("Logger initialization failed")}));
+
+    // `set_logger` calls the closure above synchronously, so `max_level_holder` is populated
+    // by the time we get here.
+    let max_level = max_level_holder.lock().unwrap().take().unwrap();
+    Ok(ReloadHandle { directives: directives_holder, max_level: max_level })
 }
 
 /// Parse a logging specification string (e.g: "crate1,crate2::mod3,crate3::x=error/foo")
 /// and return a vector with log directives.
-fn parse_logging_spec(spec: &str) -> (Vec<LogDirective>, Option<Regex>) {
+fn parse_logging_spec(spec: &str) -> (Vec<LogDirective>, Option<(FilterMode, Regex)>) {
     let mut dirs = Vec::new();
 
     let mut parts = spec.split('/');
@@ -291,8 +790,13 @@ fn parse_logging_spec(spec: &str) -> (Vec<LogDirective>, Option<Regex>) {
     }});
 
     let filter = filter.map_or(None, |filter| {
-        match Regex::new(filter) {
-            Ok(re) => Some(re),
+        let (mode, pattern) = if filter.starts_with('!') {
+            (FilterMode::Exclude, &filter[1..])
+        } else {
+            (FilterMode::Include, filter)
+        };
+        match Regex::new(pattern) {
+            Ok(re) => Some((mode, re)),
             Err(e) => {
                 println!("warning: invalid regex filter - {}", e);
                 None
@@ -302,3 +806,69 @@ fn parse_logging_spec(spec: &str) -> (Vec<LogDirective>, Option<Regex>) {
 
     return (dirs, filter);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn parse_logging_spec_plain_pattern_is_include_only() {
+        let (_, filter) = parse_logging_spec("info/^foo");
+        match filter {
+            Some((FilterMode::Include, ref re)) => assert!(re.is_match("foobar")),
+            _ => panic!("expected an include-only filter"),
+        }
+    }
+
+    #[test]
+    fn parse_logging_spec_bang_prefixed_pattern_is_exclude() {
+        let (_, filter) = parse_logging_spec("info/!^foo");
+        match filter {
+            Some((FilterMode::Exclude, ref re)) => assert!(re.is_match("foobar")),
+            _ => panic!("expected an exclude filter"),
+        }
+    }
+
+    #[test]
+    fn parse_logging_spec_without_a_slash_has_no_filter() {
+        let (_, filter) = parse_logging_spec("info");
+        assert!(filter.is_none());
+    }
+
+    #[test]
+    fn rotate_if_needed_leaves_file_open_under_the_size_threshold() {
+        let basename = env::temp_dir().join(format!("flexi_logger_test_{}_a", process::id())).to_string_lossy().into_owned();
+        let initial_path = format!("{}.trc", basename);
+        let writer = FileLogWriter::new(basename.clone(), &initial_path, Some(10), false, RotateNaming::Numbers, None);
+
+        let mut state = writer.state.lock().unwrap();
+        writer.rotate_if_needed(&mut state, 3);
+        assert_eq!(state.path, initial_path);
+        drop(state);
+
+        let _ = fs::remove_file(&initial_path);
+    }
+
+    #[test]
+    fn rotate_if_needed_keeps_the_active_file_name_constant_for_numbers_naming() {
+        let basename = env::temp_dir().join(format!("flexi_logger_test_{}_b", process::id())).to_string_lossy().into_owned();
+        let initial_path = format!("{}.trc", basename);
+        let writer = FileLogWriter::new(basename.clone(), &initial_path, Some(10), false, RotateNaming::Numbers, None);
+
+        let mut state = writer.state.lock().unwrap();
+        state.written_bytes = 8;
+        writer.rotate_if_needed(&mut state, 5); // 8 + 5 > 10
+        // Numbers naming always reopens the same, unsuffixed active path...
+        assert_eq!(state.path, initial_path);
+        assert_eq!(state.written_bytes, 0);
+        drop(state);
+
+        // ...while the file that held the old contents was renamed aside, not truncated.
+        assert!(Path::new(&format!("{}.1.trc", basename)).exists());
+
+        let _ = fs::remove_file(&initial_path);
+        let _ = fs::remove_file(format!("{}.1.trc", basename));
+    }
+}